@@ -1,60 +1,292 @@
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
 use std::env;
+use std::fmt;
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, Read, Write};
 use std::process;
+use std::rc::Rc;
+
+/// Magic header identifying a compact binary program image.
+const BIN_MAGIC: &[u8; 4] = b"TMVB";
+const BIN_VERSION: u8 = 1;
+
+fn write_i64_be(buf: &mut Vec<u8>, value: i64) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn read_i64_be(bytes: &[u8], pos: &mut usize) -> io::Result<i64> {
+    if *pos + 8 > bytes.len() {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated binary image"));
+    }
+    let mut arr = [0u8; 8];
+    arr.copy_from_slice(&bytes[*pos..*pos + 8]);
+    *pos += 8;
+    Ok(i64::from_be_bytes(arr))
+}
+
+fn read_u8(bytes: &[u8], pos: &mut usize) -> io::Result<u8> {
+    if *pos >= bytes.len() {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated binary image"));
+    }
+    let byte = bytes[*pos];
+    *pos += 1;
+    Ok(byte)
+}
+
+/// Encodes one memory cell as `tag, payload...`, re-parsing it through
+/// `Instruction::parse` first. Round-tripping a cell through
+/// `encode_cell`/`decode_cell` preserves its meaning, not necessarily its
+/// exact source text: operands are stored as parsed integers, so e.g.
+/// `succ $007` decodes back as `succ $7` (and whitespace is normalized to
+/// single spaces). A data cell whose text happens to parse as an
+/// instruction is therefore saved and reloaded as that instruction's
+/// canonical mnemonic.
+fn encode_cell(cell: &str) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    if let Some(instruction) = Instruction::parse(cell) {
+        match instruction {
+            Instruction::Exit => buf.push(3),
+            Instruction::Succ { target, indirect } => {
+                let operand = target.parse::<i64>().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("non-numeric succ operand: {}", target))
+                })?;
+                buf.push(1);
+                buf.push(indirect as u8);
+                write_i64_be(&mut buf, operand);
+            }
+            Instruction::BeqzPred { test, test_indirect, jump, jump_indirect } => {
+                let test_operand = test.parse::<i64>().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("non-numeric beqz-pred test operand: {}", test))
+                })?;
+                let jump_operand = jump.parse::<i64>().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("non-numeric beqz-pred jump operand: {}", jump))
+                })?;
+                buf.push(2);
+                buf.push((test_indirect as u8) | ((jump_indirect as u8) << 1));
+                write_i64_be(&mut buf, test_operand);
+                write_i64_be(&mut buf, jump_operand);
+            }
+            Instruction::Call { target, indirect } => {
+                let operand = target.parse::<i64>().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("non-numeric call operand: {}", target))
+                })?;
+                buf.push(4);
+                buf.push(indirect as u8);
+                write_i64_be(&mut buf, operand);
+            }
+            Instruction::Ret => buf.push(5),
+            Instruction::Native { id, arg, arg_indirect } => {
+                let operand = arg.parse::<i64>().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("non-numeric native operand: {}", arg))
+                })?;
+                buf.push(6);
+                write_i64_be(&mut buf, id);
+                buf.push(arg_indirect as u8);
+                write_i64_be(&mut buf, operand);
+            }
+        }
+    } else if let Ok(value) = cell.parse::<i64>() {
+        buf.push(0);
+        write_i64_be(&mut buf, value);
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("cannot encode cell as binary: {}", cell),
+        ));
+    }
+    Ok(buf)
+}
+
+fn decode_cell(bytes: &[u8], pos: &mut usize) -> io::Result<String> {
+    match read_u8(bytes, pos)? {
+        0 => Ok(read_i64_be(bytes, pos)?.to_string()),
+        1 => {
+            let flags = read_u8(bytes, pos)?;
+            let operand = read_i64_be(bytes, pos)?;
+            let sigil = if flags & 1 != 0 { "&" } else { "$" };
+            Ok(format!("succ {}{}", sigil, operand))
+        }
+        2 => {
+            let flags = read_u8(bytes, pos)?;
+            let test_operand = read_i64_be(bytes, pos)?;
+            let jump_operand = read_i64_be(bytes, pos)?;
+            let test_sigil = if flags & 1 != 0 { "&" } else { "$" };
+            let jump_sigil = if flags & 2 != 0 { "&" } else { "$" };
+            Ok(format!("beqz-pred {}{} {}{}", test_sigil, test_operand, jump_sigil, jump_operand))
+        }
+        3 => Ok("exit".to_string()),
+        4 => {
+            let flags = read_u8(bytes, pos)?;
+            let operand = read_i64_be(bytes, pos)?;
+            let sigil = if flags & 1 != 0 { "&" } else { "$" };
+            Ok(format!("call {}{}", sigil, operand))
+        }
+        5 => Ok("ret".to_string()),
+        6 => {
+            let id = read_i64_be(bytes, pos)?;
+            let flags = read_u8(bytes, pos)?;
+            let operand = read_i64_be(bytes, pos)?;
+            let sigil = if flags & 1 != 0 { "&" } else { "$" };
+            Ok(format!("native {} {}{}", id, sigil, operand))
+        }
+        tag => Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown cell tag: {}", tag))),
+    }
+}
+
+/// A host callback for `native <id> <arg>`, given the VM's memory and the
+/// resolved argument address.
+type NativeHandler = Box<dyn FnMut(&mut Vec<String>, i64) -> Result<(), Trap>>;
+
+fn is_binary_image(filename: &str) -> bool {
+    let Ok(mut file) = fs::File::open(filename) else {
+        return false;
+    };
+    let mut header = [0u8; 4];
+    file.read_exact(&mut header).is_ok() && &header == BIN_MAGIC
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Trap {
+    OutOfBounds { addr: i64, size: usize },
+    InvalidInstruction { pc: i64, text: String },
+    ExecutedData { pc: i64, value: String },
+    NonIntegerIndirect { addr: i64, text: String },
+    InvalidAddress { text: String },
+    CallStackUnderflow { pc: i64 },
+    UnknownNative { id: i64, pc: i64 },
+    NativeError { message: String },
+    ArithmeticOverflow { addr: i64, value: i64 },
+    Halted,
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trap::OutOfBounds { addr, size } => write!(
+                f,
+                "Memory access out of bounds: address {} is beyond memory size {}",
+                addr, size
+            ),
+            Trap::InvalidInstruction { pc, text } => {
+                write!(f, "Invalid instruction at PC={}: {}", pc, text)
+            }
+            Trap::ExecutedData { pc, value } => write!(
+                f,
+                "Trying to execute data value {} at PC={} as instruction",
+                value, pc
+            ),
+            Trap::NonIntegerIndirect { addr, text } => write!(
+                f,
+                "Expected integer at address {} for indirect addressing, found: {}",
+                addr, text
+            ),
+            Trap::InvalidAddress { text } => write!(f, "Invalid address: {}", text),
+            Trap::CallStackUnderflow { pc } => {
+                write!(f, "ret with empty call stack at PC={}", pc)
+            }
+            Trap::UnknownNative { id, pc } => {
+                write!(f, "Unknown native {} called at PC={}", id, pc)
+            }
+            Trap::NativeError { message } => write!(f, "Native call failed: {}", message),
+            Trap::ArithmeticOverflow { addr, value } => write!(
+                f,
+                "Arithmetic overflow updating address {} (value {})",
+                addr, value
+            ),
+            Trap::Halted => write!(f, "Exit instruction encountered"),
+        }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
 enum Instruction {
     Succ { target: String, indirect: bool },
     BeqzPred { test: String, test_indirect: bool, jump: String, jump_indirect: bool },
+    Call { target: String, indirect: bool },
+    Ret,
+    Native { id: i64, arg: String, arg_indirect: bool },
     Exit,
 }
 
 impl Instruction {
     fn parse(s: &str) -> Option<Self> {
         let s = s.trim();
-        
+
         if s == "exit" {
             return Some(Instruction::Exit);
         }
-        
+
+        if s == "ret" {
+            return Some(Instruction::Ret);
+        }
+
         let parts: Vec<&str> = s.split_whitespace().collect();
-        
-        if parts.len() >= 2 && parts[0] == "succ" {
+
+        if parts.len() >= 3 && parts[0] == "native" {
+            let id = parts[1].parse::<i64>().ok()?;
+            let arg = parts[2];
+            arg.strip_prefix('&')
+                .map(|rest| Instruction::Native {
+                    id,
+                    arg: rest.to_string(),
+                    arg_indirect: true,
+                })
+                .or_else(|| {
+                    arg.strip_prefix('$').map(|rest| Instruction::Native {
+                        id,
+                        arg: rest.to_string(),
+                        arg_indirect: false,
+                    })
+                })
+        } else if parts.len() >= 2 && parts[0] == "call" {
             let target = parts[1];
-            if target.starts_with("&") {
-                Some(Instruction::Succ {
-                    target: target[1..].to_string(),
+            target
+                .strip_prefix('&')
+                .map(|rest| Instruction::Call {
+                    target: rest.to_string(),
                     indirect: true,
                 })
-            } else if target.starts_with("$") {
-                Some(Instruction::Succ {
-                    target: target[1..].to_string(),
-                    indirect: false,
+                .or_else(|| {
+                    target.strip_prefix('$').map(|rest| Instruction::Call {
+                        target: rest.to_string(),
+                        indirect: false,
+                    })
+                })
+        } else if parts.len() >= 2 && parts[0] == "succ" {
+            let target = parts[1];
+            target
+                .strip_prefix('&')
+                .map(|rest| Instruction::Succ {
+                    target: rest.to_string(),
+                    indirect: true,
+                })
+                .or_else(|| {
+                    target.strip_prefix('$').map(|rest| Instruction::Succ {
+                        target: rest.to_string(),
+                        indirect: false,
+                    })
                 })
-            } else {
-                None
-            }
         } else if parts.len() >= 3 && parts[0] == "beqz-pred" {
             let test = parts[1];
             let jump = parts[2];
-            
-            let (test_val, test_indirect) = if test.starts_with("&") {
-                (test[1..].to_string(), true)
-            } else if test.starts_with("$") {
-                (test[1..].to_string(), false)
+
+            let (test_val, test_indirect) = if let Some(rest) = test.strip_prefix('&') {
+                (rest.to_string(), true)
+            } else if let Some(rest) = test.strip_prefix('$') {
+                (rest.to_string(), false)
             } else {
                 return None;
             };
-            
-            let (jump_val, jump_indirect) = if jump.starts_with("&") {
-                (jump[1..].to_string(), true)
-            } else if jump.starts_with("$") {
-                (jump[1..].to_string(), false)
+
+            let (jump_val, jump_indirect) = if let Some(rest) = jump.strip_prefix('&') {
+                (rest.to_string(), true)
+            } else if let Some(rest) = jump.strip_prefix('$') {
+                (rest.to_string(), false)
             } else {
                 return None;
             };
-            
+
             Some(Instruction::BeqzPred {
                 test: test_val,
                 test_indirect,
@@ -67,106 +299,354 @@ impl Instruction {
     }
 }
 
+#[derive(Debug, Clone)]
+enum StepRecord {
+    Succ { pc: i64, target_addr: i64, previous: String },
+    BeqzPred { pc: i64, test_addr: i64, branch_taken: bool, previous: String },
+    Call { pc: i64 },
+    Ret { pc: i64, return_addr: i64 },
+    Native { pc: i64, addr: i64, previous: String },
+}
+
 struct VM {
     pc: i64,
     memory: Vec<String>,
+    breakpoints: HashSet<i64>,
+    watchpoints: HashMap<i64, String>,
+    journal: Vec<StepRecord>,
+    call_stack: Vec<i64>,
+    step_count: Rc<Cell<u64>>,
+    natives: HashMap<i64, NativeHandler>,
 }
 
 impl VM {
     fn new(pc: i64, memory: Vec<String>) -> Self {
-        VM { pc, memory }
+        VM {
+            pc,
+            memory,
+            breakpoints: HashSet::new(),
+            watchpoints: HashMap::new(),
+            journal: Vec::new(),
+            call_stack: Vec::new(),
+            step_count: Rc::new(Cell::new(0)),
+            natives: HashMap::new(),
+        }
     }
 
-    fn get_address(&self, addr_str: &str, indirect: bool) -> i64 {
-        let addr = addr_str.parse::<i64>().unwrap_or_else(|_| {
-            panic!("Invalid address: {}", addr_str);
-        });
-        
+    /// Registers a host callback for `native <id> <arg>` to dispatch to.
+    /// The callback receives the VM's memory and the resolved argument
+    /// address so it can read or write the cell directly. `back()` only
+    /// journals and restores the cell at `addr`, so handlers must confine
+    /// their writes to that cell; a handler that mutates any other cell
+    /// will not have that write undone.
+    fn register_native<F>(&mut self, id: i64, handler: F)
+    where
+        F: FnMut(&mut Vec<String>, i64) -> Result<(), Trap> + 'static,
+    {
+        self.natives.insert(id, Box::new(handler));
+    }
+
+    /// A shared handle to the step counter, for native handlers that report
+    /// it back to a running program (e.g. a "steps so far" syscall).
+    fn step_count_handle(&self) -> Rc<Cell<u64>> {
+        Rc::clone(&self.step_count)
+    }
+
+    fn get_address(&self, addr_str: &str, indirect: bool) -> Result<i64, Trap> {
+        let addr = addr_str.parse::<i64>().map_err(|_| Trap::InvalidAddress {
+            text: addr_str.to_string(),
+        })?;
+
         if indirect {
-            self.check_bounds(addr);
+            self.check_bounds(addr)?;
             let value_str = &self.memory[addr as usize];
-            value_str.parse::<i64>().unwrap_or_else(|_| {
-                panic!("Expected integer at address {} for indirect addressing, found: {}", addr, value_str);
+            value_str.parse::<i64>().map_err(|_| Trap::NonIntegerIndirect {
+                addr,
+                text: value_str.clone(),
             })
         } else {
-            addr
+            Ok(addr)
         }
     }
 
-    fn check_bounds(&self, addr: i64) {
+    fn check_bounds(&self, addr: i64) -> Result<(), Trap> {
         if addr < 0 || addr >= self.memory.len() as i64 {
-            panic!("Memory access out of bounds: address {} is beyond memory size {}", 
-                   addr, self.memory.len());
+            Err(Trap::OutOfBounds {
+                addr,
+                size: self.memory.len(),
+            })
+        } else {
+            Ok(())
         }
     }
 
-    fn execute_instruction(&mut self) -> bool {
-        self.check_bounds(self.pc);
+    fn execute_instruction(&mut self) -> Result<bool, Trap> {
+        self.check_bounds(self.pc)?;
+        let entry_pc = self.pc;
         let instruction_str = self.memory[self.pc as usize].clone();
-        
-        let instruction = Instruction::parse(&instruction_str).unwrap_or_else(|| {
+
+        let instruction = Instruction::parse(&instruction_str).ok_or_else(|| {
             if instruction_str.parse::<i64>().is_ok() {
-                panic!("Trying to execute data value {} at PC={} as instruction", instruction_str, self.pc);
+                Trap::ExecutedData {
+                    pc: self.pc,
+                    value: instruction_str.clone(),
+                }
             } else {
-                panic!("Invalid instruction at PC={}: {}", self.pc, instruction_str);
+                Trap::InvalidInstruction {
+                    pc: self.pc,
+                    text: instruction_str.clone(),
+                }
             }
-        });
-        
+        })?;
+
         println!("PC={}, Executing: {:?}", self.pc, instruction);
-        
+
         match instruction {
             Instruction::Exit => {
-                println!("Exit instruction encountered");
-                return false;
+                return Err(Trap::Halted);
             }
             Instruction::Succ { target, indirect } => {
-                let target_addr = self.get_address(&target, indirect);
-                self.check_bounds(target_addr);
-                
-                let current_val = self.memory[target_addr as usize].parse::<i64>()
-                    .unwrap_or(0);
-                self.memory[target_addr as usize] = (current_val + 1).to_string();
+                let target_addr = self.get_address(&target, indirect)?;
+                self.check_bounds(target_addr)?;
+
+                let previous = self.memory[target_addr as usize].clone();
+                let current_val = previous.parse::<i64>().unwrap_or(0);
+                let incremented = current_val.checked_add(1).ok_or(Trap::ArithmeticOverflow {
+                    addr: target_addr,
+                    value: current_val,
+                })?;
+                self.memory[target_addr as usize] = incremented.to_string();
                 self.pc += 1;
+                self.journal.push(StepRecord::Succ { pc: entry_pc, target_addr, previous });
             }
             Instruction::BeqzPred { test, test_indirect, jump, jump_indirect } => {
-                let test_addr = self.get_address(&test, test_indirect);
-                self.check_bounds(test_addr);
-                
-                let test_val = self.memory[test_addr as usize].parse::<i64>()
-                    .unwrap_or(0);
-                
+                let test_addr = self.get_address(&test, test_indirect)?;
+                self.check_bounds(test_addr)?;
+
+                let previous = self.memory[test_addr as usize].clone();
+                let test_val = previous.parse::<i64>().unwrap_or(0);
+
                 if test_val == 0 {
-                    let jump_addr = self.get_address(&jump, jump_indirect);
-                    self.check_bounds(jump_addr);
+                    let jump_addr = self.get_address(&jump, jump_indirect)?;
+                    self.check_bounds(jump_addr)?;
                     self.pc = jump_addr;
+                    self.journal.push(StepRecord::BeqzPred { pc: entry_pc, test_addr, branch_taken: true, previous });
                 } else {
-                    self.memory[test_addr as usize] = (test_val - 1).to_string();
+                    let decremented = test_val.checked_sub(1).ok_or(Trap::ArithmeticOverflow {
+                        addr: test_addr,
+                        value: test_val,
+                    })?;
+                    self.memory[test_addr as usize] = decremented.to_string();
                     self.pc += 1;
+                    self.journal.push(StepRecord::BeqzPred { pc: entry_pc, test_addr, branch_taken: false, previous });
                 }
             }
+            Instruction::Call { target, indirect } => {
+                let target_addr = self.get_address(&target, indirect)?;
+                self.check_bounds(target_addr)?;
+
+                self.call_stack.push(entry_pc + 1);
+                self.pc = target_addr;
+                self.journal.push(StepRecord::Call { pc: entry_pc });
+            }
+            Instruction::Ret => {
+                let return_addr = self.call_stack.pop()
+                    .ok_or(Trap::CallStackUnderflow { pc: entry_pc })?;
+                self.pc = return_addr;
+                self.journal.push(StepRecord::Ret { pc: entry_pc, return_addr });
+            }
+            Instruction::Native { id, arg, arg_indirect } => {
+                let addr = self.get_address(&arg, arg_indirect)?;
+                self.check_bounds(addr)?;
+
+                let previous = self.memory[addr as usize].clone();
+                let handler = self.natives.get_mut(&id)
+                    .ok_or(Trap::UnknownNative { id, pc: entry_pc })?;
+                handler(&mut self.memory, addr)?;
+                self.pc += 1;
+                self.journal.push(StepRecord::Native { pc: entry_pc, addr, previous });
+            }
         }
-        
-        true
+
+        self.step_count.set(self.step_count.get() + 1);
+        Ok(true)
     }
 
     fn print_state(&self) {
         println!("\n=== VM State ===");
         println!("PC: {}", self.pc);
+        println!("Call stack depth: {}", self.call_stack.len());
         println!("Memory:");
         for (i, val) in self.memory.iter().enumerate() {
             println!("  [{}]: {}", i, val);
         }
+        if !self.breakpoints.is_empty() {
+            let mut bps: Vec<&i64> = self.breakpoints.iter().collect();
+            bps.sort();
+            println!("Breakpoints: {:?}", bps);
+        }
+        if !self.watchpoints.is_empty() {
+            println!("Watchpoints:");
+            for (addr, last) in &self.watchpoints {
+                println!("  [{}]: last seen = {}", addr, last);
+            }
+        }
         println!("================\n");
     }
 
+    fn watch(&mut self, addr: i64) {
+        let current = if addr >= 0 && (addr as usize) < self.memory.len() {
+            self.memory[addr as usize].clone()
+        } else {
+            String::new()
+        };
+        self.watchpoints.insert(addr, current);
+    }
+
+    fn check_watchpoints(&mut self) -> Option<(i64, String)> {
+        let mut hit = None;
+        for (&addr, last) in self.watchpoints.iter_mut() {
+            if addr >= 0 && (addr as usize) < self.memory.len() {
+                let current = &self.memory[addr as usize];
+                if current != last {
+                    if hit.is_none() {
+                        hit = Some((addr, current.clone()));
+                    }
+                    *last = current.clone();
+                }
+            }
+        }
+        hit
+    }
+
     fn run_steps(&mut self, steps: usize) {
         for _ in 0..steps {
-            if !self.execute_instruction() {
-                self.print_state();
+            match self.execute_instruction() {
+                Ok(_) => {
+                    if let Some((addr, value)) = self.check_watchpoints() {
+                        println!("Watchpoint hit: memory[{}] changed to {}", addr, value);
+                        return;
+                    }
+                }
+                Err(trap) => {
+                    println!("Trap at PC={}: {}", self.pc, trap);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn continue_run(&mut self) {
+        loop {
+            match self.execute_instruction() {
+                Ok(_) => {}
+                Err(trap) => {
+                    println!("Trap at PC={}: {}", self.pc, trap);
+                    return;
+                }
+            }
+            if let Some((addr, value)) = self.check_watchpoints() {
+                println!("Watchpoint hit: memory[{}] changed to {}", addr, value);
+                return;
+            }
+            if self.breakpoints.contains(&self.pc) {
+                println!("Breakpoint hit at PC={}", self.pc);
+                return;
+            }
+        }
+    }
+
+    /// Keeps a watchpoint's cached "last seen" value in sync with memory
+    /// that `back` just restored, so replaying forward doesn't miss a hit
+    /// against a now-stale cached value.
+    fn sync_watchpoint(&mut self, addr: i64) {
+        if let Some(last) = self.watchpoints.get_mut(&addr) {
+            *last = self.memory[addr as usize].clone();
+        }
+    }
+
+    fn back(&mut self, n: usize) {
+        for _ in 0..n {
+            let Some(record) = self.journal.pop() else {
+                println!("Nothing to undo");
                 return;
+            };
+            self.step_count.set(self.step_count.get().saturating_sub(1));
+            match record {
+                StepRecord::Succ { pc, target_addr, previous } => {
+                    self.memory[target_addr as usize] = previous;
+                    self.pc = pc;
+                    self.sync_watchpoint(target_addr);
+                }
+                StepRecord::BeqzPred { pc, test_addr, branch_taken, previous } => {
+                    if !branch_taken {
+                        self.memory[test_addr as usize] = previous;
+                        self.sync_watchpoint(test_addr);
+                    }
+                    self.pc = pc;
+                }
+                StepRecord::Call { pc } => {
+                    self.call_stack.pop();
+                    self.pc = pc;
+                }
+                StepRecord::Ret { pc, return_addr } => {
+                    self.call_stack.push(return_addr);
+                    self.pc = pc;
+                }
+                StepRecord::Native { pc, addr, previous } => {
+                    self.memory[addr as usize] = previous;
+                    self.pc = pc;
+                    self.sync_watchpoint(addr);
+                }
+            }
+        }
+    }
+
+    fn load_binary(path: &str) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        if bytes.len() < 5 || &bytes[0..4] != BIN_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a tmvm binary image"));
+        }
+        if bytes[4] != BIN_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported binary image version: {}", bytes[4]),
+            ));
+        }
+        let mut pos = 5;
+        let pc = read_i64_be(&bytes, &mut pos)?;
+        let count = read_i64_be(&bytes, &mut pos)?;
+        let mut memory = Vec::with_capacity(count.max(0) as usize);
+        for _ in 0..count {
+            memory.push(decode_cell(&bytes, &mut pos)?);
+        }
+        Ok(VM::new(pc, memory))
+    }
+
+    fn save_binary(&self, path: &str) -> io::Result<()> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(BIN_MAGIC);
+        buf.push(BIN_VERSION);
+        write_i64_be(&mut buf, self.pc);
+        write_i64_be(&mut buf, self.memory.len() as i64);
+        for cell in &self.memory {
+            buf.extend_from_slice(&encode_cell(cell)?);
+        }
+        fs::write(path, buf)
+    }
+
+    fn disasm(&self, start: i64, end: i64) {
+        for addr in start..=end {
+            if addr < 0 || addr as usize >= self.memory.len() {
+                println!("[{}]: <out of bounds>", addr);
+                continue;
+            }
+            let text = &self.memory[addr as usize];
+            match Instruction::parse(text) {
+                Some(_) => println!("[{}]: {}", addr, text),
+                None => println!("[{}]: {} (data)", addr, text),
             }
         }
-        self.print_state();
     }
 }
 
@@ -188,44 +668,473 @@ fn main() {
         process::exit(1);
     }
     
-    let pc = args[1].parse::<i64>().unwrap_or_else(|_| {
-        eprintln!("Invalid PC value: {}", args[1]);
-        process::exit(1);
-    });
-    
-    let memory = load_memory_from_file(&args[2]).unwrap_or_else(|e| {
-        eprintln!("Failed to load memory file: {}", e);
-        process::exit(1);
-    });
-    
-    let mut vm = VM::new(pc, memory);
-    
+    let mut vm = if is_binary_image(&args[2]) {
+        println!("Binary image detected: ignoring <initial_pc> argument, using the PC stored in the image");
+        VM::load_binary(&args[2]).unwrap_or_else(|e| {
+            eprintln!("Failed to load binary image: {}", e);
+            process::exit(1);
+        })
+    } else {
+        let pc = args[1].parse::<i64>().unwrap_or_else(|_| {
+            eprintln!("Invalid PC value: {}", args[1]);
+            process::exit(1);
+        });
+        let memory = load_memory_from_file(&args[2]).unwrap_or_else(|e| {
+            eprintln!("Failed to load memory file: {}", e);
+            process::exit(1);
+        });
+        VM::new(pc, memory)
+    };
+
+    register_default_natives(&mut vm);
+
     println!("Turing Machine VM initialized");
     vm.print_state();
-    
+    print_help();
+
     loop {
-        print!("Enter number of steps to execute (or 'q' to quit): ");
+        print!("(tmvm) ");
         io::stdout().flush().unwrap();
-        
+
         let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
-        let input = input.trim();
-        
-        if input == "q" || input == "quit" {
+        if io::stdin().read_line(&mut input).unwrap() == 0 {
             break;
         }
-        
-        match input.parse::<usize>() {
-            Ok(steps) => {
-                if steps == 0 {
-                    println!("Please enter a positive number of steps");
-                    continue;
+        let input = input.trim();
+        let parts: Vec<&str> = input.split_whitespace().collect();
+
+        match parts.as_slice() {
+            [] => {}
+            ["q"] | ["quit"] => break,
+            ["help"] => print_help(),
+            ["step"] => vm.run_steps(1),
+            ["step", n] => match n.parse::<usize>() {
+                Ok(n) => vm.run_steps(n),
+                Err(_) => println!("Invalid step count: {}", n),
+            },
+            ["continue"] => vm.continue_run(),
+            ["back"] => vm.back(1),
+            ["back", n] => match n.parse::<usize>() {
+                Ok(n) => vm.back(n),
+                Err(_) => println!("Invalid step count: {}", n),
+            },
+            ["break", pc_str] => match pc_str.parse::<i64>() {
+                Ok(pc) => {
+                    vm.breakpoints.insert(pc);
+                    println!("Breakpoint set at PC={}", pc);
                 }
-                vm.run_steps(steps);
-            }
-            Err(_) => {
-                println!("Invalid input. Please enter a number or 'q' to quit");
-            }
+                Err(_) => println!("Invalid PC: {}", pc_str),
+            },
+            ["delete", pc_str] => match pc_str.parse::<i64>() {
+                Ok(pc) => {
+                    if vm.breakpoints.remove(&pc) {
+                        println!("Breakpoint at PC={} removed", pc);
+                    } else {
+                        println!("No breakpoint at PC={}", pc);
+                    }
+                }
+                Err(_) => println!("Invalid PC: {}", pc_str),
+            },
+            ["watch", addr_str] => match addr_str.parse::<i64>() {
+                Ok(addr) => {
+                    vm.watch(addr);
+                    println!("Watchpoint set at [{}]", addr);
+                }
+                Err(_) => println!("Invalid address: {}", addr_str),
+            },
+            ["mem", addr_str] => match addr_str.parse::<i64>() {
+                Ok(addr) => {
+                    if addr >= 0 && (addr as usize) < vm.memory.len() {
+                        println!("[{}]: {}", addr, vm.memory[addr as usize]);
+                    } else {
+                        println!("{}", Trap::OutOfBounds { addr, size: vm.memory.len() });
+                    }
+                }
+                Err(_) => println!("Invalid address: {}", addr_str),
+            },
+            ["mem", addr_str, rest @ ..] if !rest.is_empty() => match addr_str.parse::<i64>() {
+                Ok(addr) => {
+                    if addr >= 0 && (addr as usize) < vm.memory.len() {
+                        vm.memory[addr as usize] = rest.join(" ");
+                    } else {
+                        println!("{}", Trap::OutOfBounds { addr, size: vm.memory.len() });
+                    }
+                }
+                Err(_) => println!("Invalid address: {}", addr_str),
+            },
+            ["pc", value] => match value.parse::<i64>() {
+                Ok(new_pc) => vm.pc = new_pc,
+                Err(_) => println!("Invalid PC value: {}", value),
+            },
+            ["reg"] | ["state"] => vm.print_state(),
+            ["save", path] => match vm.save_binary(path) {
+                Ok(()) => println!("Saved binary image to {}", path),
+                Err(e) => println!("Failed to save binary image: {}", e),
+            },
+            ["disasm", start, end] => match (start.parse::<i64>(), end.parse::<i64>()) {
+                (Ok(start), Ok(end)) => vm.disasm(start, end),
+                _ => println!("Invalid range: {} {}", start, end),
+            },
+            _ => println!("Unknown command: {} (try 'help')", input),
         }
     }
+}
+
+/// Registers the stock `native` handlers: 1 = print a cell as an integer,
+/// 2 = print a cell as a char, 3 = read an integer from stdin into a cell,
+/// 4 = write the current step count into a cell.
+fn register_default_natives(vm: &mut VM) {
+    vm.register_native(1, |memory, addr| {
+        let value = memory[addr as usize].parse::<i64>().unwrap_or(0);
+        println!("{}", value);
+        Ok(())
+    });
+
+    vm.register_native(2, |memory, addr| {
+        let value = memory[addr as usize].parse::<i64>().unwrap_or(0);
+        let code = u32::try_from(value)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| Trap::NativeError {
+                message: format!("{} is not a valid char code", value),
+            })?;
+        print!("{}", code);
+        io::stdout().flush().unwrap();
+        Ok(())
+    });
+
+    vm.register_native(3, |memory, addr| {
+        let mut input = String::new();
+        io::stdin().read_line(&mut input).map_err(|e| Trap::NativeError {
+            message: format!("failed to read stdin: {}", e),
+        })?;
+        let value = input.trim().parse::<i64>().map_err(|_| Trap::NativeError {
+            message: format!("not an integer: {}", input.trim()),
+        })?;
+        memory[addr as usize] = value.to_string();
+        Ok(())
+    });
+
+    let step_count = vm.step_count_handle();
+    vm.register_native(4, move |memory, addr| {
+        memory[addr as usize] = step_count.get().to_string();
+        Ok(())
+    });
+}
+
+fn print_help() {
+    println!("Commands:");
+    println!("  step [n]              execute n instructions (default 1)");
+    println!("  continue               run until a breakpoint or watchpoint fires");
+    println!("  back [n]               undo n executed instructions (default 1)");
+    println!("  break <pc>             set a breakpoint at pc");
+    println!("  delete <pc>            remove the breakpoint at pc");
+    println!("  watch <addr>           halt when memory[addr] changes");
+    println!("  mem <addr> [value]     read, or poke, a memory cell");
+    println!("  pc <value>             set the program counter");
+    println!("  reg | state            dump the machine state");
+    println!("  save <file>            save memory and pc as a binary image");
+    println!("  disasm <start> <end>   decode cells [start, end] as instructions");
+    println!("  q | quit               exit the debugger");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn back_restores_literal_previous_cell_text() {
+        // [0] succ $2, [1] exit, [2] a non-canonical integer cell.
+        let memory = vec!["succ $2".to_string(), "exit".to_string(), "007".to_string()];
+        let mut vm = VM::new(0, memory);
+        vm.watch(2);
+
+        vm.run_steps(1);
+        assert_eq!(vm.memory[2], "8");
+        assert_eq!(vm.pc, 1);
+
+        vm.back(1);
+        assert_eq!(vm.memory[2], "007", "back() must restore the exact prior text, not a reparsed integer");
+        assert_eq!(vm.pc, 0);
+        assert_eq!(vm.watchpoints[&2], "007", "watchpoint cache must resync to the restored text");
+    }
+
+    #[test]
+    fn back_past_a_taken_branch_restores_pc_without_touching_memory() {
+        // [0] beqz-pred $1 $2 takes the branch since memory[1] == 0.
+        let memory = vec!["beqz-pred $1 $2".to_string(), "0".to_string(), "exit".to_string()];
+        let mut vm = VM::new(0, memory);
+
+        vm.run_steps(1);
+        assert_eq!(vm.pc, 2);
+        assert_eq!(vm.memory[1], "0");
+
+        vm.back(1);
+        assert_eq!(vm.pc, 0);
+        assert_eq!(vm.memory[1], "0");
+    }
+
+    fn roundtrip(cell: &str) -> String {
+        let encoded = encode_cell(cell).unwrap();
+        let mut pos = 0;
+        let decoded = decode_cell(&encoded, &mut pos).unwrap();
+        assert_eq!(pos, encoded.len(), "decode_cell should consume exactly what encode_cell wrote");
+        decoded
+    }
+
+    #[test]
+    fn encode_decode_roundtrips_every_instruction_shape() {
+        assert_eq!(roundtrip("42"), "42");
+        assert_eq!(roundtrip("-7"), "-7");
+        assert_eq!(roundtrip("exit"), "exit");
+        assert_eq!(roundtrip("ret"), "ret");
+        assert_eq!(roundtrip("succ $3"), "succ $3");
+        assert_eq!(roundtrip("succ &3"), "succ &3");
+        assert_eq!(roundtrip("beqz-pred $1 &2"), "beqz-pred $1 &2");
+        assert_eq!(roundtrip("call &4"), "call &4");
+        assert_eq!(roundtrip("native 9 $5"), "native 9 $5");
+    }
+
+    #[test]
+    fn encode_decode_normalizes_non_canonical_operand_text() {
+        // Documented lossy case: operands are stored as parsed integers, so
+        // leading zeros and extra whitespace don't survive the round trip.
+        assert_eq!(roundtrip("succ $007"), "succ $7");
+        assert_eq!(roundtrip("succ   $1"), "succ $1");
+    }
+
+    #[test]
+    fn save_and_load_binary_roundtrips_a_program() {
+        let dir = env::temp_dir();
+        let path = dir.join(format!("tmvm_test_{}.bin", process::id()));
+        let path = path.to_str().unwrap();
+
+        let memory = vec![
+            "succ $1".to_string(),
+            "0".to_string(),
+            "beqz-pred $1 &3".to_string(),
+            "exit".to_string(),
+        ];
+        let vm = VM::new(2, memory.clone());
+        vm.save_binary(path).unwrap();
+
+        let loaded = VM::load_binary(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(loaded.pc, 2);
+        assert_eq!(loaded.memory, memory);
+    }
+
+    #[test]
+    fn continue_run_steps_past_a_breakpoint_on_the_second_call() {
+        // [0] succ $4, [1] succ $4 (breakpoint here), [2] succ $4, [3] exit, [4] counter.
+        let memory = vec![
+            "succ $4".to_string(),
+            "succ $4".to_string(),
+            "succ $4".to_string(),
+            "exit".to_string(),
+            "0".to_string(),
+        ];
+        let mut vm = VM::new(0, memory);
+        vm.breakpoints.insert(1);
+
+        vm.continue_run();
+        assert_eq!(vm.pc, 1, "should stop at the breakpoint hit after executing instruction 0");
+        assert_eq!(vm.memory[4], "1");
+
+        vm.continue_run();
+        assert_eq!(vm.pc, 3, "a second continue must step past the breakpoint and run to the exit trap");
+        assert_eq!(vm.memory[4], "3");
+    }
+
+    #[test]
+    fn continue_run_steps_past_a_watchpoint_on_the_second_call() {
+        // [0] succ $3, [1] succ $3, [2] exit, [3] watched counter.
+        let memory = vec![
+            "succ $3".to_string(),
+            "succ $3".to_string(),
+            "exit".to_string(),
+            "0".to_string(),
+        ];
+        let mut vm = VM::new(0, memory);
+        vm.watch(3);
+
+        vm.continue_run();
+        assert_eq!(vm.pc, 1, "should stop right after the watched cell first changes");
+        assert_eq!(vm.memory[3], "1");
+
+        vm.continue_run();
+        assert_eq!(vm.pc, 2, "a second continue must see the watchpoint already synced and run to the next change");
+        assert_eq!(vm.memory[3], "2");
+    }
+
+    #[test]
+    fn call_and_ret_round_trip_through_the_call_stack() {
+        // [0] call $2, [1] exit, [2] succ $4, [3] ret, [4] counter.
+        let memory = vec![
+            "call $2".to_string(),
+            "exit".to_string(),
+            "succ $4".to_string(),
+            "ret".to_string(),
+            "0".to_string(),
+        ];
+        let mut vm = VM::new(0, memory);
+
+        vm.run_steps(1);
+        assert_eq!(vm.pc, 2, "call should jump to the resolved target");
+        assert_eq!(vm.call_stack, vec![1], "call should push the return address");
+
+        vm.run_steps(1);
+        assert_eq!(vm.memory[4], "1");
+        assert_eq!(vm.pc, 3);
+
+        vm.run_steps(1);
+        assert_eq!(vm.pc, 1, "ret should pop the call stack back into pc");
+        assert!(vm.call_stack.is_empty());
+    }
+
+    #[test]
+    fn ret_with_empty_call_stack_traps() {
+        let memory = vec!["ret".to_string()];
+        let mut vm = VM::new(0, memory);
+
+        let err = vm.execute_instruction().unwrap_err();
+        assert_eq!(err, Trap::CallStackUnderflow { pc: 0 });
+    }
+
+    #[test]
+    fn back_undoes_a_call_ret_pair_restoring_pc_and_call_stack() {
+        // [0] call $2, [1] exit, [2] ret.
+        let memory = vec!["call $2".to_string(), "exit".to_string(), "ret".to_string()];
+        let mut vm = VM::new(0, memory);
+
+        vm.run_steps(1);
+        assert_eq!(vm.pc, 2);
+        assert_eq!(vm.call_stack, vec![1]);
+
+        vm.run_steps(1);
+        assert_eq!(vm.pc, 1, "ret should have returned to the call site's next instruction");
+        assert!(vm.call_stack.is_empty());
+
+        vm.back(1);
+        assert_eq!(vm.pc, 2, "undoing ret should restore pc to just before it ran");
+        assert_eq!(vm.call_stack, vec![1], "undoing ret should restore the popped return address");
+
+        vm.back(1);
+        assert_eq!(vm.pc, 0, "undoing call should restore pc to the call site");
+        assert!(vm.call_stack.is_empty(), "undoing call should pop the return address it pushed");
+    }
+
+    #[test]
+    fn native_dispatch_calls_the_registered_handler_and_back_undoes_it() {
+        // [0] native 1 $1, [1] the cell the handler mutates.
+        let memory = vec!["native 1 $1".to_string(), "0".to_string()];
+        let mut vm = VM::new(0, memory);
+        vm.register_native(1, |memory, addr| {
+            memory[addr as usize] = "42".to_string();
+            Ok(())
+        });
+
+        vm.run_steps(1);
+        assert_eq!(vm.memory[1], "42", "the registered handler should have run and mutated the cell");
+        assert_eq!(vm.pc, 1);
+
+        vm.back(1);
+        assert_eq!(vm.memory[1], "0", "back() should restore the cell the native handler wrote");
+        assert_eq!(vm.pc, 0);
+    }
+
+    #[test]
+    fn native_with_unknown_id_traps() {
+        let memory = vec!["native 9 $1".to_string(), "0".to_string()];
+        let mut vm = VM::new(0, memory);
+
+        let err = vm.execute_instruction().unwrap_err();
+        assert_eq!(err, Trap::UnknownNative { id: 9, pc: 0 });
+    }
+
+    #[test]
+    fn out_of_bounds_address_traps_instead_of_panicking() {
+        let memory = vec!["succ $5".to_string(), "0".to_string()];
+        let mut vm = VM::new(0, memory);
+
+        let err = vm.execute_instruction().unwrap_err();
+        assert_eq!(err, Trap::OutOfBounds { addr: 5, size: 2 });
+    }
+
+    #[test]
+    fn unparseable_cell_traps_as_invalid_instruction() {
+        let memory = vec!["not an instruction".to_string()];
+        let mut vm = VM::new(0, memory);
+
+        let err = vm.execute_instruction().unwrap_err();
+        assert_eq!(
+            err,
+            Trap::InvalidInstruction { pc: 0, text: "not an instruction".to_string() }
+        );
+    }
+
+    #[test]
+    fn executing_a_data_cell_traps_as_executed_data() {
+        let memory = vec!["42".to_string()];
+        let mut vm = VM::new(0, memory);
+
+        let err = vm.execute_instruction().unwrap_err();
+        assert_eq!(err, Trap::ExecutedData { pc: 0, value: "42".to_string() });
+    }
+
+    #[test]
+    fn non_integer_indirect_target_traps() {
+        // [0] succ &1 resolves indirectly through memory[1], which isn't an integer.
+        let memory = vec!["succ &1".to_string(), "not-a-number".to_string()];
+        let mut vm = VM::new(0, memory);
+
+        let err = vm.execute_instruction().unwrap_err();
+        assert_eq!(
+            err,
+            Trap::NonIntegerIndirect { addr: 1, text: "not-a-number".to_string() }
+        );
+    }
+
+    #[test]
+    fn succ_on_i64_max_traps_as_arithmetic_overflow() {
+        let memory = vec!["succ $1".to_string(), i64::MAX.to_string()];
+        let mut vm = VM::new(0, memory);
+
+        let err = vm.execute_instruction().unwrap_err();
+        assert_eq!(err, Trap::ArithmeticOverflow { addr: 1, value: i64::MAX });
+    }
+
+    #[test]
+    fn beqz_pred_decrement_from_i64_min_traps_as_arithmetic_overflow() {
+        // [0] beqz-pred $1 $2 takes the decrement path since memory[1] != 0.
+        let memory = vec![
+            "beqz-pred $1 $2".to_string(),
+            i64::MIN.to_string(),
+            "exit".to_string(),
+        ];
+        let mut vm = VM::new(0, memory);
+
+        let err = vm.execute_instruction().unwrap_err();
+        assert_eq!(err, Trap::ArithmeticOverflow { addr: 1, value: i64::MIN });
+    }
+
+    #[test]
+    fn exit_traps_as_halted() {
+        let memory = vec!["exit".to_string()];
+        let mut vm = VM::new(0, memory);
+
+        let err = vm.execute_instruction().unwrap_err();
+        assert_eq!(err, Trap::Halted);
+    }
+
+    #[test]
+    fn non_numeric_address_operand_traps_as_invalid_address() {
+        // [0] succ $abc has a non-numeric operand, which used to panic in get_address().
+        let memory = vec!["succ $abc".to_string()];
+        let mut vm = VM::new(0, memory);
+
+        let err = vm.execute_instruction().unwrap_err();
+        assert_eq!(err, Trap::InvalidAddress { text: "abc".to_string() });
+    }
 }
\ No newline at end of file